@@ -0,0 +1,8 @@
+use std::path::PathBuf;
+
+use crate::rate_limit::RateLimiter;
+
+pub struct Global {
+    pub storage_root: PathBuf,
+    pub rate_limiter: RateLimiter,
+}
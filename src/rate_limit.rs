@@ -0,0 +1,128 @@
+use std::{collections::HashMap, sync::Arc, sync::Mutex, time::Instant};
+
+use axum::{
+    extract::State,
+    http::{header::RETRY_AFTER, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+
+use crate::{
+    global::Global,
+    utils::{err_response, ErrorReason, Username},
+};
+
+pub struct RateLimitConfig {
+    pub capacity: f64,
+    pub refill_per_sec: f64,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from `username`'s bucket, refilling it lazily based
+    /// on time elapsed since the last request. On exhaustion, returns the
+    /// number of seconds until a token becomes available.
+    fn try_acquire(&self, username: &str) -> Result<(), f64> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        let bucket = buckets.entry(username.to_string()).or_insert_with(|| TokenBucket {
+            tokens: self.config.capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.config.refill_per_sec).min(self.config.capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err((1.0 - bucket.tokens) / self.config.refill_per_sec)
+        }
+    }
+}
+
+pub async fn rate_limit<B>(
+    State(global): State<Arc<Global>>,
+    Extension(Username(username)): Extension<Username>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match global.rate_limiter.try_acquire(&username) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            let mut resp =
+                err_response(StatusCode::TOO_MANY_REQUESTS, ErrorReason::TooManyRequests429);
+            resp.headers_mut().insert(
+                RETRY_AFTER,
+                retry_after.ceil().to_string().parse().unwrap(),
+            );
+            resp
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_request_is_allowed_from_a_full_bucket() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            capacity: 2.0,
+            refill_per_sec: 1.0,
+        });
+        assert!(limiter.try_acquire("alice").is_ok());
+    }
+
+    #[test]
+    fn exhausting_the_bucket_rejects_with_a_retry_after() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            capacity: 1.0,
+            refill_per_sec: 1.0,
+        });
+        assert!(limiter.try_acquire("alice").is_ok());
+        let retry_after = limiter.try_acquire("alice").unwrap_err();
+        assert!(retry_after > 0.0);
+    }
+
+    #[test]
+    fn buckets_are_independent_per_user() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            capacity: 1.0,
+            refill_per_sec: 1.0,
+        });
+        assert!(limiter.try_acquire("alice").is_ok());
+        assert!(limiter.try_acquire("alice").is_err());
+        assert!(limiter.try_acquire("bob").is_ok());
+    }
+
+    #[test]
+    fn refills_lazily_over_time() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            capacity: 1.0,
+            refill_per_sec: 1000.0,
+        });
+        assert!(limiter.try_acquire("alice").is_ok());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(limiter.try_acquire("alice").is_ok());
+    }
+}
@@ -0,0 +1,61 @@
+use std::{io::ErrorKind, sync::Arc};
+
+use axum::{
+    body::StreamBody,
+    extract::State,
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use tokio::{fs::File, io::AsyncReadExt};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    global::Global,
+    utils::{err_response, hash_path, sniff_mime, ErrorReason},
+};
+
+const SNIFF_LEN: usize = 4096;
+
+pub async fn get_by_digest(
+    State(global): State<Arc<Global>>,
+    axum::extract::Path(digest): axum::extract::Path<String>,
+) -> Response {
+    let path = match hash_path(&global, &digest) {
+        Ok(p) => p,
+        Err(e) => return *e,
+    };
+    eprintln!("get_by_digest:{}", path.display());
+    let mut file = match File::open(&path).await {
+        Ok(f) => f,
+        Err(e) => {
+            let (code, reason) = if e.kind() == ErrorKind::NotFound {
+                (StatusCode::NOT_FOUND, ErrorReason::NotFound404)
+            } else {
+                eprintln!("{e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500)
+            };
+            return err_response(code, reason).into_response();
+        }
+    };
+    let len = file.metadata().await.unwrap().len();
+
+    // Digests never carry an extension, so mime_guess has nothing to go
+    // on; sniff the content directly from the leading bytes instead.
+    let mut head = vec![0u8; SNIFF_LEN.min(len as usize)];
+    if let Err(e) = file.read_exact(&mut head).await {
+        eprintln!("{e}");
+        return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+    }
+    let content_type = sniff_mime(&head);
+
+    let reader = std::io::Cursor::new(head).chain(file);
+    let mut resp = StreamBody::new(ReaderStream::new(reader)).into_response();
+    resp.headers_mut()
+        .insert(CONTENT_LENGTH, len.to_string().parse().unwrap());
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, content_type.parse().unwrap());
+    resp
+}
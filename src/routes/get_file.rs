@@ -1,34 +1,91 @@
-use std::{io::ErrorKind, sync::Arc};
+use std::{
+    io::{Cursor, ErrorKind},
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+};
 
 use axum::{
     body::StreamBody,
-    extract::State,
+    extract::{Query, State},
     http::{
-        header::{CONTENT_LENGTH, CONTENT_TYPE},
-        StatusCode,
+        header::{
+            ACCEPT, ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE,
+            CONTENT_TYPE, RANGE,
+        },
+        HeaderMap, StatusCode,
     },
     response::{IntoResponse, Response},
-    Extension,
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt},
 };
-use tokio::fs::File;
 use tokio_util::io::ReaderStream;
 
 use crate::{
     global::Global,
-    utils::{err_response, sanitize_path, ErrorReason, Username},
+    utils::{
+        content_disposition_attachment, err_response, parse_range, sanitize_path, sniff_mime,
+        ErrorReason, Username,
+    },
 };
 
+const SNIFF_LEN: usize = 4096;
+
+#[derive(Deserialize)]
+pub struct IndexQuery {
+    json: Option<String>,
+    download: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DirEntryInfo {
+    name: String,
+    size: u64,
+    modified: Option<u64>,
+}
+
 pub async fn get_file(
     State(global): State<Arc<Global>>,
     Extension(Username(username)): Extension<Username>,
     axum::extract::Path(file): axum::extract::Path<String>,
+    Query(query): Query<IndexQuery>,
+    headers: HeaderMap,
 ) -> Response {
     let path = match sanitize_path(username, &global, file) {
         Ok(x) => x,
-        Err(e) => return e,
+        Err(e) => return *e,
     };
     eprintln!("get_file:{}", path.display());
-    let file = match File::open(&path).await {
+
+    let meta = match tokio::fs::metadata(&path).await {
+        Ok(m) => m,
+        Err(e) => {
+            let (code, reason) = if e.kind() == ErrorKind::NotFound {
+                (StatusCode::NOT_FOUND, ErrorReason::NotFound404)
+            } else {
+                eprintln!("{e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500)
+            };
+            return err_response(code, reason).into_response();
+        }
+    };
+
+    if meta.is_dir() {
+        let wants_json = query.json.is_some() || wants_json_response(&headers);
+        return match render_directory(&path, wants_json).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                eprintln!("{e}");
+                err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response()
+            }
+        };
+    }
+
+    let mut file = match File::open(&path).await {
         Ok(f) => f,
         Err(e) => {
             let (code, reason) = if e.kind() == ErrorKind::NotFound {
@@ -40,13 +97,146 @@ pub async fn get_file(
             return err_response(code, reason).into_response();
         }
     };
-    let len = file.metadata().await.unwrap().len();
-    let mut resp = StreamBody::new(ReaderStream::new(file)).into_response();
+    let len = meta.len();
+
+    let mut head = vec![0u8; SNIFF_LEN.min(len as usize)];
+    if let Err(e) = file.read_exact(&mut head).await {
+        eprintln!("{e}");
+        return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+    }
+
+    let content_type = match mime_guess::from_path(&path).first() {
+        Some(mime) if mime != mime::APPLICATION_OCTET_STREAM => mime.to_string(),
+        _ => sniff_mime(&head).to_string(),
+    };
+
+    let range = headers
+        .get(RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| parse_range(v, len));
+
+    let (status, start, slice_len) = match range {
+        None => (StatusCode::OK, 0, len),
+        Some(Err(_)) => {
+            let mut resp =
+                err_response(StatusCode::RANGE_NOT_SATISFIABLE, ErrorReason::RangeNotSatisfiable416);
+            resp.headers_mut()
+                .insert(CONTENT_RANGE, format!("bytes */{len}").parse().unwrap());
+            resp.headers_mut().insert(ACCEPT_RANGES, "bytes".parse().unwrap());
+            return resp;
+        }
+        Some(Ok(r)) => (StatusCode::PARTIAL_CONTENT, r.start, r.end - r.start + 1),
+    };
+
+    let reader: Pin<Box<dyn AsyncRead + Send>> = if start == 0 {
+        Box::pin(Cursor::new(head).chain(file).take(slice_len))
+    } else {
+        if let Err(e) = file.seek(std::io::SeekFrom::Start(start)).await {
+            eprintln!("{e}");
+            return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+        }
+        Box::pin(file.take(slice_len))
+    };
+
+    let mut resp = StreamBody::new(ReaderStream::new(reader)).into_response();
+    *resp.status_mut() = status;
+    resp.headers_mut()
+        .insert(CONTENT_LENGTH, slice_len.to_string().parse().unwrap());
+    resp.headers_mut()
+        .insert(ACCEPT_RANGES, "bytes".parse().unwrap());
+    if status == StatusCode::PARTIAL_CONTENT {
+        resp.headers_mut().insert(
+            CONTENT_RANGE,
+            format!("bytes {start}-{}/{len}", start + slice_len - 1)
+                .parse()
+                .unwrap(),
+        );
+    }
     resp.headers_mut()
-        .insert(CONTENT_LENGTH, len.to_string().parse().unwrap());
-    if let Some(mime) = mime_guess::from_path(path).first() {
-        resp.headers_mut()
-            .insert(CONTENT_TYPE, mime.to_string().parse().unwrap());
+        .insert(CONTENT_TYPE, content_type.parse().unwrap());
+    if let Some(download) = &query.download {
+        let filename = if download.is_empty() {
+            path.file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        } else {
+            download.clone()
+        };
+        resp.headers_mut().insert(
+            CONTENT_DISPOSITION,
+            content_disposition_attachment(&filename).parse().unwrap(),
+        );
     }
     dbg!(resp)
 }
+
+fn wants_json_response(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map(|accept| accept.contains("application/json") && !accept.contains("text/html"))
+        .unwrap_or(false)
+}
+
+async fn list_dir(path: &Path) -> std::io::Result<Vec<DirEntryInfo>> {
+    let mut entries = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(path).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        let meta = entry.metadata().await?;
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        entries.push(DirEntryInfo {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            size: meta.len(),
+            modified,
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+async fn render_directory(path: &Path, wants_json: bool) -> std::io::Result<Response> {
+    let entries = list_dir(path).await?;
+    if wants_json {
+        return Ok(Json(entries).into_response());
+    }
+    let mut html = String::from("<!DOCTYPE html><html><head><meta charset=\"utf-8\"></head><body><ul>");
+    for entry in &entries {
+        let href = percent_encode_segment(&entry.name);
+        let name = html_escape(&entry.name);
+        let modified = entry
+            .modified
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        html.push_str(&format!(
+            "<li><a href=\"{href}\">{name}</a> {size} {modified}</li>",
+            size = entry.size,
+        ));
+    }
+    html.push_str("</ul></body></html>");
+    Ok(([(CONTENT_TYPE, "text/html; charset=utf-8")], html).into_response())
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Percent-encodes a single path segment for use in an `href`, so names
+/// containing `#`, `?`, `&`, or other reserved characters link to the
+/// entry itself instead of being parsed as a fragment/query/separator.
+fn percent_encode_segment(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
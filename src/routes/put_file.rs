@@ -0,0 +1,99 @@
+use std::{fmt::Write as _, sync::Arc};
+
+use axum::{
+    extract::{BodyStream, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    global::Global,
+    utils::{err_response, hash_path, ErrorReason, Username},
+};
+
+#[derive(Serialize)]
+pub struct PutFileResponse {
+    pub digest: String,
+}
+
+pub async fn put_file(
+    State(global): State<Arc<Global>>,
+    Extension(Username(_username)): Extension<Username>,
+    mut body: BodyStream,
+) -> Response {
+    let tmp_dir = global.storage_root.join("tmp");
+    if let Err(e) = tokio::fs::create_dir_all(&tmp_dir).await {
+        eprintln!("{e}");
+        return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+    }
+    let tmp_path = tmp_dir.join(format!(
+        "{}-{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+
+    let mut tmp_file = match tokio::fs::File::create(&tmp_path).await {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{e}");
+            return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+        }
+    };
+
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = match chunk {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("{e}");
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+            }
+        };
+        hasher.update(&chunk);
+        if let Err(e) = tmp_file.write_all(&chunk).await {
+            eprintln!("{e}");
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+        }
+    }
+    if let Err(e) = tmp_file.flush().await {
+        eprintln!("{e}");
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+    }
+
+    let digest = to_hex(&hasher.finalize());
+    let final_path = match hash_path(&global, &digest) {
+        Ok(p) => p,
+        Err(e) => return *e,
+    };
+    if let Some(parent) = final_path.parent() {
+        if let Err(e) = tokio::fs::create_dir_all(parent).await {
+            eprintln!("{e}");
+            return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+        }
+    }
+    if let Err(e) = tokio::fs::rename(&tmp_path, &final_path).await {
+        eprintln!("{e}");
+        return err_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorReason::Error500).into_response();
+    }
+
+    (StatusCode::CREATED, Json(PutFileResponse { digest })).into_response()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(s, "{b:02x}").unwrap();
+    }
+    s
+}
@@ -0,0 +1,325 @@
+use std::path::PathBuf;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::global::Global;
+
+#[derive(Clone)]
+pub struct Username(pub String);
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorReason {
+    NotFound404,
+    Error500,
+    Forbidden403,
+    RangeNotSatisfiable416,
+    TooManyRequests429,
+    BadRequest400,
+}
+
+pub fn err_response(code: StatusCode, reason: ErrorReason) -> Response {
+    (code, Json(reason)).into_response()
+}
+
+pub fn sanitize_path(
+    username: String,
+    global: &Global,
+    file: String,
+) -> Result<PathBuf, Box<Response>> {
+    let root = global.storage_root.join(&username);
+    let Ok(canonical_root) = root.canonicalize() else {
+        return Err(Box::new(err_response(
+            StatusCode::NOT_FOUND,
+            ErrorReason::NotFound404,
+        )));
+    };
+    let joined = root.join(file.trim_start_matches('/'));
+    match joined.canonicalize() {
+        Ok(p) if p.starts_with(&canonical_root) => Ok(p),
+        _ => Err(Box::new(err_response(
+            StatusCode::FORBIDDEN,
+            ErrorReason::Forbidden403,
+        ))),
+    }
+}
+
+/// Classifies a file by its leading bytes. Used as a fallback when
+/// extension-based guessing comes up empty, so extensionless or
+/// renamed files still get a sensible `Content-Type`.
+pub fn sniff_mime(head: &[u8]) -> &'static str {
+    if head.starts_with(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']) {
+        "image/png"
+    } else if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if head.starts_with(b"%PDF") {
+        "application/pdf"
+    } else if head.starts_with(&[0x1f, 0x8b]) {
+        "application/gzip"
+    } else if std::str::from_utf8(head).is_ok() {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+#[cfg(test)]
+mod sniff_mime_tests {
+    use super::*;
+
+    #[test]
+    fn detects_png() {
+        assert_eq!(
+            sniff_mime(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0, 0]),
+            "image/png"
+        );
+    }
+
+    #[test]
+    fn detects_jpeg() {
+        assert_eq!(sniff_mime(&[0xFF, 0xD8, 0xFF, 0xE0]), "image/jpeg");
+    }
+
+    #[test]
+    fn detects_pdf() {
+        assert_eq!(sniff_mime(b"%PDF-1.7"), "application/pdf");
+    }
+
+    #[test]
+    fn detects_gzip() {
+        assert_eq!(sniff_mime(&[0x1f, 0x8b, 0x08]), "application/gzip");
+    }
+
+    #[test]
+    fn falls_back_to_text_plain_for_utf8() {
+        assert_eq!(sniff_mime(b"hello world"), "text/plain");
+    }
+
+    #[test]
+    fn falls_back_to_octet_stream_for_binary() {
+        assert_eq!(sniff_mime(&[0xff, 0xfe, 0x00, 0xff]), "application/octet-stream");
+    }
+
+    #[test]
+    fn empty_head_is_text_plain() {
+        assert_eq!(sniff_mime(&[]), "text/plain");
+    }
+}
+
+/// Builds a `Content-Disposition: attachment` header value, with an ASCII
+/// fallback `filename` and an RFC 5987 `filename*=UTF-8''...` that carries
+/// the full name. Both parameters are always emitted, so names containing
+/// non-ASCII characters survive intact in `filename*`.
+pub fn content_disposition_attachment(filename: &str) -> String {
+    let ascii_fallback: String = filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded = rfc5987_encode(filename);
+    format!("attachment; filename=\"{ascii_fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+#[cfg(test)]
+mod content_disposition_tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn plain_ascii_name_round_trips() {
+        let value = content_disposition_attachment("report.pdf");
+        assert_eq!(
+            value,
+            "attachment; filename=\"report.pdf\"; filename*=UTF-8''report.pdf"
+        );
+        HeaderValue::from_str(&value).unwrap();
+    }
+
+    #[test]
+    fn control_characters_do_not_produce_an_invalid_header_value() {
+        let value = content_disposition_attachment("evil\r\nX-Injected: 1");
+        HeaderValue::from_str(&value).unwrap();
+    }
+
+    #[test]
+    fn non_ascii_name_gets_rfc5987_encoding() {
+        let value = content_disposition_attachment("caf\u{e9}.txt");
+        assert!(value.contains("filename*=UTF-8''caf%C3%A9.txt"));
+        HeaderValue::from_str(&value).unwrap();
+    }
+
+    #[test]
+    fn quotes_and_backslashes_are_neutralized_in_the_ascii_fallback() {
+        let value = content_disposition_attachment("a\"b\\c");
+        HeaderValue::from_str(&value).unwrap();
+    }
+}
+
+fn rfc5987_encode(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'!' | b'#' | b'$' | b'&' | b'+' | b'-'
+            | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+const DIGEST_HEX_LEN: usize = 64;
+
+fn is_lowercase_hex(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Maps a SHA-256 hex digest to its storage location, sharded by the
+/// first two hex characters. Rejects anything that isn't a well-formed
+/// digest so caller-supplied strings can never escape `storage_root`.
+pub fn hash_path(global: &Global, digest: &str) -> Result<PathBuf, Box<Response>> {
+    if digest.len() != DIGEST_HEX_LEN || !is_lowercase_hex(digest) {
+        return Err(Box::new(err_response(
+            StatusCode::BAD_REQUEST,
+            ErrorReason::BadRequest400,
+        )));
+    }
+    let (shard, rest) = digest.split_at(2);
+    Ok(global.storage_root.join("cas").join(shard).join(rest))
+}
+
+#[cfg(test)]
+mod hash_path_tests {
+    use super::*;
+
+    fn global() -> Global {
+        Global {
+            storage_root: PathBuf::from("/tmp/archiv-test-root"),
+            rate_limiter: crate::rate_limit::RateLimiter::new(crate::rate_limit::RateLimitConfig {
+                capacity: 1.0,
+                refill_per_sec: 1.0,
+            }),
+        }
+    }
+
+    #[test]
+    fn valid_digest_is_sharded() {
+        let digest = "a".repeat(64);
+        let path = hash_path(&global(), &digest).unwrap();
+        assert_eq!(
+            path,
+            PathBuf::from("/tmp/archiv-test-root/cas/aa").join("a".repeat(62))
+        );
+    }
+
+    #[test]
+    fn too_short_digest_is_rejected() {
+        assert!(hash_path(&global(), "a").is_err());
+    }
+
+    #[test]
+    fn path_traversal_digest_is_rejected() {
+        assert!(hash_path(&global(), "..").is_err());
+        assert!(hash_path(&global(), "../../../../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn uppercase_digest_is_rejected() {
+        let digest = "A".repeat(64);
+        assert!(hash_path(&global(), &digest).is_err());
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParsedRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RangeUnsatisfiable;
+
+/// Parses a `Range: bytes=start-end` header against a known total length.
+/// Only a single range is supported; anything else is rejected so the
+/// caller can answer with `416 Range Not Satisfiable`.
+pub fn parse_range(header: &str, total: u64) -> Result<ParsedRange, RangeUnsatisfiable> {
+    let spec = header.strip_prefix("bytes=").ok_or(RangeUnsatisfiable)?;
+    if spec.contains(',') {
+        return Err(RangeUnsatisfiable);
+    }
+    let (start_s, end_s) = spec.split_once('-').ok_or(RangeUnsatisfiable)?;
+    if start_s.is_empty() {
+        return Err(RangeUnsatisfiable);
+    }
+    let start: u64 = start_s.parse().map_err(|_| RangeUnsatisfiable)?;
+    let end = if end_s.is_empty() {
+        total.saturating_sub(1)
+    } else {
+        end_s
+            .parse::<u64>()
+            .map_err(|_| RangeUnsatisfiable)?
+            .min(total.saturating_sub(1))
+    };
+    if total == 0 || start > end || start >= total {
+        return Err(RangeUnsatisfiable);
+    }
+    Ok(ParsedRange { start, end })
+}
+
+#[cfg(test)]
+mod parse_range_tests {
+    use super::*;
+
+    #[test]
+    fn full_file() {
+        let r = parse_range("bytes=0-99", 100).unwrap();
+        assert_eq!((r.start, r.end), (0, 99));
+    }
+
+    #[test]
+    fn open_ended_reads_to_eof() {
+        let r = parse_range("bytes=50-", 100).unwrap();
+        assert_eq!((r.start, r.end), (50, 99));
+    }
+
+    #[test]
+    fn end_beyond_total_is_clamped() {
+        let r = parse_range("bytes=0-1000", 100).unwrap();
+        assert_eq!((r.start, r.end), (0, 99));
+    }
+
+    #[test]
+    fn multiple_ranges_are_unsatisfiable() {
+        assert_eq!(parse_range("bytes=0-10,20-30", 100), Err(RangeUnsatisfiable));
+    }
+
+    #[test]
+    fn start_past_eof_is_unsatisfiable() {
+        assert_eq!(parse_range("bytes=100-200", 100), Err(RangeUnsatisfiable));
+    }
+
+    #[test]
+    fn missing_start_is_unsatisfiable() {
+        assert_eq!(parse_range("bytes=-100", 100), Err(RangeUnsatisfiable));
+    }
+
+    #[test]
+    fn garbage_header_is_unsatisfiable() {
+        assert_eq!(parse_range("not-a-range", 100), Err(RangeUnsatisfiable));
+    }
+
+    #[test]
+    fn zero_length_file_is_unsatisfiable() {
+        assert_eq!(parse_range("bytes=0-", 0), Err(RangeUnsatisfiable));
+    }
+}